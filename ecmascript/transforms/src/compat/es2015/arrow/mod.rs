@@ -1,6 +1,8 @@
 use crate::util::ExprFactory;
 use ast::*;
-use swc_common::{Fold, FoldWith, Visit, VisitWith, DUMMY_SP};
+use std::collections::HashSet;
+use swc_common::errors::HANDLER;
+use swc_common::{Fold, FoldWith, Spanned, Visit, VisitWith, DUMMY_SP};
 
 #[cfg(test)]
 mod tests;
@@ -53,18 +55,373 @@ mod tests;
 /// };
 /// console.log(bob.printFriends());
 /// ```
-pub fn arrow() -> impl Fold<Expr> {
-    Arrow
+pub fn arrow() -> impl Fold<Module> {
+    Arrow::default()
 }
 
-#[derive(Debug, Clone, Copy)]
-struct Arrow;
+/// Lowers arrow functions to plain `function`s, hoisting one shared capture
+/// per enclosing function/constructor/accessor/program scope for everything
+/// an arrow would otherwise lose by becoming its own `this`-binding
+/// function: `this`, `arguments`, `super.prop`, and `new.target`.
+#[derive(Debug, Default)]
+struct Arrow {
+    /// Scopes of enclosing non-arrow functions, constructors, accessors (and
+    /// the program), innermost last. Arrows don't push a scope of their own,
+    /// since all of the above are lexically inherited through any number of
+    /// nested arrows.
+    scopes: Vec<Scope>,
+}
+
+#[derive(Debug, Default)]
+struct Scope {
+    /// Set the first time an arrow in this scope references `this`; every
+    /// other arrow in the same scope reuses this identifier so they all
+    /// close over one hoisted variable.
+    this_ident: Option<Ident>,
+    /// Same idea as `this_ident`, for a bare `arguments` reference.
+    arguments_ident: Option<Ident>,
+    /// Same idea as `this_ident`, for a `new.target` meta-property.
+    new_target_ident: Option<Ident>,
+    /// One hoisted capture per distinct `super.prop` read from an arrow in
+    /// this scope, keyed by property name and in first-seen order.
+    super_props: Vec<(Ident, Ident)>,
+    /// Number of arrow bodies we're currently folding, relative to this
+    /// scope. Captures are only substituted in while this is non-zero, so a
+    /// bare `this`/`arguments`/`super.prop`/`new.target` in the owning
+    /// function itself is left untouched.
+    arrow_depth: usize,
+    /// Names already bound somewhere in this scope (params, `var`/function/
+    /// class declarations, ...) before we hoist anything into it, so a
+    /// hoisted capture can be renamed instead of silently shadowing one of
+    /// them.
+    bound_names: HashSet<String>,
+}
+
+impl Arrow {
+    fn in_arrow(&self) -> bool {
+        self.scopes.last().map_or(false, |scope| scope.arrow_depth > 0)
+    }
+
+    fn scope(&mut self) -> &mut Scope {
+        self.scopes
+            .last_mut()
+            .expect("captures are only ever resolved inside a tracked scope")
+    }
+
+    /// A name starting with `base` that isn't already bound in the current
+    /// scope, bumping a numeric suffix (`_this`, `_this2`, `_this3`, ...)
+    /// until one is free.
+    fn fresh_ident(&mut self, base: &str) -> Ident {
+        let scope = self.scope();
+
+        let mut name = base.to_string();
+        let mut suffix = 2;
+        while scope.bound_names.contains(&name) {
+            name = format!("{}{}", base, suffix);
+            suffix += 1;
+        }
+
+        scope.bound_names.insert(name.clone());
+        Ident {
+            span: DUMMY_SP,
+            sym: name.into(),
+        }
+    }
+
+    /// Identifier an arrow in the current scope should use in place of
+    /// `this`, allocating it the first time it's needed.
+    fn this_ident(&mut self) -> Ident {
+        if let Some(ident) = self.scope().this_ident.clone() {
+            return ident;
+        }
+
+        let ident = self.fresh_ident("_this");
+        self.scope().this_ident = Some(ident.clone());
+        ident
+    }
+
+    /// Identifier an arrow in the current scope should use in place of a
+    /// bare `arguments` reference, allocating it the first time it's needed.
+    fn arguments_ident(&mut self) -> Ident {
+        if let Some(ident) = self.scope().arguments_ident.clone() {
+            return ident;
+        }
+
+        let ident = self.fresh_ident("_arguments");
+        self.scope().arguments_ident = Some(ident.clone());
+        ident
+    }
+
+    /// Identifier an arrow in the current scope should use in place of
+    /// `new.target`, allocating it the first time it's needed.
+    fn new_target_ident(&mut self) -> Ident {
+        if let Some(ident) = self.scope().new_target_ident.clone() {
+            return ident;
+        }
+
+        let ident = self.fresh_ident("_newtarget");
+        self.scope().new_target_ident = Some(ident.clone());
+        ident
+    }
+
+    /// Identifier an arrow in the current scope should use in place of
+    /// `super.<prop>`, allocating a fresh capture for that property name the
+    /// first time it's needed.
+    fn super_prop_ident(&mut self, prop: &Ident) -> Ident {
+        let existing = self
+            .scope()
+            .super_props
+            .iter()
+            .find(|(name, _)| name.sym == prop.sym)
+            .map(|(_, ident)| ident.clone());
+        if let Some(ident) = existing {
+            return ident;
+        }
+
+        let ident = self.fresh_ident(&format!("_superprop_get{}", capitalize(&prop.sym)));
+        self.scope().super_props.push((prop.clone(), ident.clone()));
+        ident
+    }
+
+    /// Pushes a fresh scope seeded with the names already bound by `names`,
+    /// so hoisted captures can avoid colliding with them.
+    fn push_scope(&mut self, names: HashSet<String>) {
+        self.scopes.push(Scope {
+            bound_names: names,
+            ..Scope::default()
+        });
+    }
+
+    fn pop_scope(&mut self) -> Scope {
+        self.scopes.pop().expect("we just pushed this scope")
+    }
+}
+
+impl Fold<Module> for Arrow {
+    fn fold(&mut self, module: Module) -> Module {
+        self.push_scope(bound_names(&module.body));
+        let mut body = module.body.fold_with(self);
+        let scope = self.pop_scope();
+
+        let hoisted = hoisted_stmts(scope);
+        body.splice(0..0, hoisted.into_iter().map(ModuleItem::Stmt));
+
+        Module { body, ..module }
+    }
+}
+
+impl Fold<Function> for Arrow {
+    fn fold(&mut self, f: Function) -> Function {
+        let mut names = bound_names(&f.params);
+        names.extend(bound_names(&f.body.stmts));
+        self.push_scope(names);
+
+        let mut f = f.fold_children(self);
+        let scope = self.pop_scope();
+
+        insert_hoisted(&mut f.body.stmts, hoisted_stmts(scope));
+        f
+    }
+}
+
+impl Fold<Constructor> for Arrow {
+    fn fold(&mut self, mut c: Constructor) -> Constructor {
+        let body = match c.body.take() {
+            Some(body) => body,
+            // An ambient/overload constructor signature has no body to hoist
+            // into.
+            None => return c,
+        };
+        let BlockStmt {
+            span: body_span,
+            stmts,
+        } = body;
+
+        let mut names = bound_names(&c.params);
+        names.extend(bound_names(&stmts));
+        self.push_scope(names);
+
+        c.params = c.params.fold_with(self);
+        let mut stmts = stmts.fold_with(self);
+        let scope = self.pop_scope();
+
+        // A derived class's constructor can open with `super(...)`, which
+        // must run before `this` is accessed - splicing our hoisted `var`s
+        // ahead of it would throw "must call super constructor before
+        // accessing 'this'" at runtime, so they go right after it instead.
+        insert_hoisted(&mut stmts, hoisted_stmts(scope));
+
+        c.body = Some(BlockStmt {
+            span: body_span,
+            stmts,
+        });
+        c
+    }
+}
+
+impl Fold<GetterProp> for Arrow {
+    fn fold(&mut self, mut getter: GetterProp) -> GetterProp {
+        let body = match getter.body.take() {
+            Some(body) => body,
+            None => return getter,
+        };
+        let BlockStmt {
+            span: body_span,
+            stmts,
+        } = body;
+
+        self.push_scope(bound_names(&stmts));
+        let mut stmts = stmts.fold_with(self);
+        let scope = self.pop_scope();
+
+        insert_hoisted(&mut stmts, hoisted_stmts(scope));
+
+        getter.body = Some(BlockStmt {
+            span: body_span,
+            stmts,
+        });
+        getter
+    }
+}
+
+impl Fold<SetterProp> for Arrow {
+    fn fold(&mut self, mut setter: SetterProp) -> SetterProp {
+        let body = match setter.body.take() {
+            Some(body) => body,
+            None => return setter,
+        };
+        let BlockStmt {
+            span: body_span,
+            stmts,
+        } = body;
+
+        let mut names = bound_names(&setter.param);
+        names.extend(bound_names(&stmts));
+        self.push_scope(names);
+
+        setter.param = setter.param.fold_with(self);
+        let mut stmts = stmts.fold_with(self);
+        let scope = self.pop_scope();
+
+        insert_hoisted(&mut stmts, hoisted_stmts(scope));
+
+        setter.body = Some(BlockStmt {
+            span: body_span,
+            stmts,
+        });
+        setter
+    }
+}
 
 impl Fold<Expr> for Arrow {
     fn fold(&mut self, e: Expr) -> Expr {
-        let e = e.fold_children(self);
-
         match e {
+            Expr::This(this_expr) => {
+                if self.in_arrow() {
+                    Expr::Ident(self.this_ident())
+                } else {
+                    Expr::This(this_expr)
+                }
+            }
+
+            Expr::Ident(ident) => {
+                if self.in_arrow() && &*ident.sym == "arguments" {
+                    Expr::Ident(self.arguments_ident())
+                } else {
+                    Expr::Ident(ident)
+                }
+            }
+
+            Expr::MetaProp(MetaPropExpr { meta, prop }) => {
+                if self.in_arrow() && &*meta.sym == "new" && &*prop.sym == "target" {
+                    Expr::Ident(self.new_target_ident())
+                } else {
+                    Expr::MetaProp(MetaPropExpr { meta, prop })
+                }
+            }
+
+            // `super.prop(args)` - special-cased ahead of the plain member
+            // access below so the call keeps `this` as its receiver instead
+            // of becoming a bare call of the captured function value.
+            Expr::Call(CallExpr {
+                span,
+                callee: ExprOrSuper::Expr(box Expr::Member(MemberExpr {
+                    obj: ExprOrSuper::Super(_),
+                    prop: box Expr::Ident(prop),
+                    computed: false,
+                    ..
+                })),
+                args,
+            }) if self.in_arrow() => {
+                let capture = self.super_prop_ident(&prop);
+                let args = args.fold_with(self);
+
+                Expr::Call(CallExpr {
+                    span,
+                    callee: Expr::Member(MemberExpr {
+                        span: DUMMY_SP,
+                        obj: ExprOrSuper::Expr(box Expr::Ident(capture)),
+                        prop: box quote_ident!("call").into(),
+                        computed: false,
+                    })
+                    .as_callee(),
+                    // This ends up inside the `function` the enclosing arrow
+                    // is about to become, so it must be the hoisted `_this`
+                    // capture, not a bare `this` - a bare one would resolve
+                    // to whatever the new unbound function is called with.
+                    args: std::iter::once(Expr::Ident(self.this_ident()).as_arg())
+                        .chain(args)
+                        .collect(),
+                })
+            }
+
+            // A plain `super.prop` read.
+            Expr::Member(MemberExpr {
+                obj: ExprOrSuper::Super(_),
+                prop: box Expr::Ident(prop),
+                computed: false,
+                ..
+            }) if self.in_arrow() => Expr::Ident(self.super_prop_ident(&prop)),
+
+            // Computed `super[prop]` access/calls aren't captured. Report it
+            // through the normal diagnostic path and leave the node
+            // untouched, instead of aborting the whole compiler process on
+            // otherwise-valid ES2015 input - the rest of the file (and any
+            // other files in the same build) can still be processed.
+            e @ Expr::Member(MemberExpr {
+                obj: ExprOrSuper::Super(_),
+                computed: true,
+                ..
+            }) if self.in_arrow() => {
+                emit_unsupported_super_error(e.span());
+                e
+            }
+            e @ Expr::Call(CallExpr {
+                callee: ExprOrSuper::Expr(box Expr::Member(MemberExpr {
+                    obj: ExprOrSuper::Super(_),
+                    computed: true,
+                    ..
+                })),
+                ..
+            }) if self.in_arrow() => {
+                emit_unsupported_super_error(e.span());
+                e
+            }
+
+            // A non-computed `obj.prop` names a property, not a binding, so
+            // only `obj` is folded - `obj.arguments` keeps its property name.
+            Expr::Member(MemberExpr {
+                span,
+                obj,
+                prop,
+                computed: false,
+            }) => Expr::Member(MemberExpr {
+                span,
+                obj: obj.fold_with(self),
+                prop,
+                computed: false,
+            }),
+
             Expr::Arrow(ArrowExpr {
                 span,
                 params,
@@ -72,9 +429,12 @@ impl Fold<Expr> for Arrow {
                 is_async,
                 is_generator,
             }) => {
-                let used_this = contains_this_expr(&body);
+                self.scope().arrow_depth += 1;
+                let params = params.fold_with(self);
+                let body = body.fold_with(self);
+                self.scope().arrow_depth -= 1;
 
-                let fn_expr = Expr::Fn(FnExpr {
+                Expr::Fn(FnExpr {
                     ident: None,
                     function: Function {
                         span,
@@ -92,51 +452,146 @@ impl Fold<Expr> for Arrow {
                             },
                         },
                     },
-                });
-
-                if !used_this {
-                    return fn_expr;
-                }
-
-                Expr::Call(CallExpr {
-                    span,
-                    callee: Expr::Member(MemberExpr {
-                        span,
-                        obj: ExprOrSuper::Expr(box fn_expr),
-                        prop: box quote_ident!("bind").into(),
-                        computed: false,
-                    })
-                    .as_callee(),
-                    args: vec![ThisExpr { span: DUMMY_SP }.as_arg()],
                 })
             }
-            _ => e,
+
+            _ => e.fold_children(self),
         }
     }
 }
 
-fn contains_this_expr(body: &BlockStmtOrExpr) -> bool {
-    struct Visitor {
-        found: bool,
+/// Reports a non-fatal diagnostic for a computed `super[prop]` access/call
+/// inside an arrow, which we don't know how to capture.
+fn emit_unsupported_super_error(span: swc_common::Span) {
+    HANDLER.with(|handler| {
+        handler.span_err(
+            span,
+            "arrow functions using computed `super[prop]` access can't be lowered yet",
+        )
+    });
+}
+
+/// Builds the `var` declarations that capture everything arrows in `scope`
+/// ended up needing, in the order they should be hoisted: `this`,
+/// `arguments`, `new.target`, then one per captured `super.prop`.
+fn hoisted_stmts(scope: Scope) -> Vec<Stmt> {
+    let mut stmts = vec![];
+
+    if let Some(ident) = scope.this_ident {
+        stmts.push(hoisted_var_decl(ident, Expr::This(ThisExpr { span: DUMMY_SP })));
     }
 
-    impl Visit<ThisExpr> for Visitor {
-        fn visit(&mut self, _: &ThisExpr) {
-            self.found = true;
-        }
+    if let Some(ident) = scope.arguments_ident {
+        stmts.push(hoisted_var_decl(ident, Expr::Ident(quote_ident!("arguments"))));
     }
 
-    impl Visit<FnExpr> for Visitor {
-        /// Don't recurse into fn
-        fn visit(&mut self, _: &FnExpr) {}
+    if let Some(ident) = scope.new_target_ident {
+        stmts.push(hoisted_var_decl(
+            ident,
+            Expr::MetaProp(MetaPropExpr {
+                meta: quote_ident!("new"),
+                prop: quote_ident!("target"),
+            }),
+        ));
     }
 
-    impl Visit<FnDecl> for Visitor {
-        /// Don't recurse into fn
-        fn visit(&mut self, _: &FnDecl) {}
+    for (prop, ident) in scope.super_props {
+        stmts.push(hoisted_var_decl(
+            ident,
+            Expr::Member(MemberExpr {
+                span: DUMMY_SP,
+                obj: ExprOrSuper::Super(Super { span: DUMMY_SP }),
+                prop: box Expr::Ident(prop),
+                computed: false,
+            }),
+        ));
     }
 
-    let mut visitor = Visitor { found: false };
-    body.visit_with(&mut visitor);
-    visitor.found
+    stmts
+}
+
+fn hoisted_var_decl(ident: Ident, init: Expr) -> Stmt {
+    Stmt::Decl(Decl::Var(VarDecl {
+        span: DUMMY_SP,
+        kind: VarDeclKind::Var,
+        decls: vec![VarDeclarator {
+            span: DUMMY_SP,
+            name: Pat::Ident(ident),
+            init: Some(box init),
+        }],
+    }))
+}
+
+/// Inserts `hoisted` into `stmts`, after a leading `super(...)` call if
+/// there is one (only constructors can have one), else at the very front.
+fn insert_hoisted(stmts: &mut Vec<Stmt>, hoisted: Vec<Stmt>) {
+    if hoisted.is_empty() {
+        return;
+    }
+
+    let insert_at = match stmts.first() {
+        Some(Stmt::Expr(ExprStmt { expr, .. })) if is_super_call(expr) => 1,
+        _ => 0,
+    };
+
+    stmts.splice(insert_at..insert_at, hoisted);
+}
+
+fn is_super_call(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::Call(CallExpr {
+            callee: ExprOrSuper::Super(_),
+            ..
+        })
+    )
+}
+
+/// Upper-cases the first character of `s`, for building names like
+/// `_superprop_getFoo` from a property named `foo`.
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Collects every identifier bound or referenced anywhere under `node`,
+/// stopping at nested function/constructor/accessor boundaries (they open
+/// their own scope, so names inside them can't collide with a capture
+/// hoisted out here). Deliberately conservative: it collects *all*
+/// identifiers, not just binding positions, so it may pick a needlessly
+/// high-numbered fresh name but will never clobber an existing binding.
+fn bound_names<N: VisitWith<BoundNames> + ?Sized>(node: &N) -> HashSet<String> {
+    let mut names = BoundNames::default();
+    node.visit_with(&mut names);
+    names.names
+}
+
+#[derive(Debug, Default)]
+struct BoundNames {
+    names: HashSet<String>,
+}
+
+impl Visit<Ident> for BoundNames {
+    fn visit(&mut self, ident: &Ident) {
+        self.names.insert(ident.sym.to_string());
+    }
+}
+
+impl Visit<Function> for BoundNames {
+    fn visit(&mut self, _: &Function) {}
+}
+
+impl Visit<Constructor> for BoundNames {
+    fn visit(&mut self, _: &Constructor) {}
+}
+
+impl Visit<GetterProp> for BoundNames {
+    fn visit(&mut self, _: &GetterProp) {}
+}
+
+impl Visit<SetterProp> for BoundNames {
+    fn visit(&mut self, _: &SetterProp) {}
 }
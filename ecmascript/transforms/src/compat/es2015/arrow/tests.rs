@@ -0,0 +1,322 @@
+use super::arrow;
+use crate::testing::{
+    assert_fold_preserves_precedence, assert_fold_reports_errors, assert_fold_round_trips, print_fold,
+};
+
+#[test]
+fn hoists_single_this_for_one_arrow() {
+    let out = print_fold(
+        arrow(),
+        r#"
+        var bob = {
+            printFriends() {
+                this._friends.forEach(f => console.log(this._name + " knows " + f));
+            }
+        };
+        "#,
+    );
+
+    assert!(out.contains("var _this = this;"), "missing hoisted _this:\n{}", out);
+    // The converted callback body must use `_this`, not a bare `this` (which
+    // would resolve to whatever `forEach` calls the new plain function
+    // with, not the original receiver).
+    assert!(
+        !out.replace("_this.", "").contains("this._name"),
+        "bare `this` survived lowering:\n{}",
+        out
+    );
+    assert!(out.contains("_this._name"), "converted body doesn't use _this:\n{}", out);
+
+    assert_fold_round_trips(
+        arrow(),
+        r#"
+        var bob = {
+            printFriends() {
+                this._friends.forEach(f => console.log(this._name + " knows " + f));
+            }
+        };
+        "#,
+    );
+}
+
+#[test]
+fn shares_one_this_across_nested_arrows() {
+    let out = print_fold(
+        arrow(),
+        r#"
+        function outer() {
+            var a = () => this.a;
+            var b = () => () => this.b;
+        }
+        "#,
+    );
+
+    // Exactly one hoisted capture, reused by every arrow in the scope.
+    assert_eq!(
+        out.matches("var _this = this;").count(),
+        1,
+        "expected exactly one hoisted _this:\n{}",
+        out
+    );
+    assert!(out.contains("_this.a"), "first arrow doesn't use _this:\n{}", out);
+    assert!(out.contains("_this.b"), "nested arrow doesn't use _this:\n{}", out);
+
+    assert_fold_round_trips(
+        arrow(),
+        r#"
+        function outer() {
+            var a = () => this.a;
+            var b = () => () => this.b;
+        }
+        "#,
+    );
+}
+
+#[test]
+fn leaves_direct_this_alone() {
+    let out = print_fold(
+        arrow(),
+        r#"
+        function outer() {
+            console.log(this);
+            return () => this;
+        }
+        "#,
+    );
+
+    // `this` used directly in the owning function must stay `this`; only
+    // the arrow's reference gets rewritten to the hoisted capture.
+    assert!(
+        out.contains("console.log(this)"),
+        "direct `this` in the owning function was rewritten:\n{}",
+        out
+    );
+    assert!(out.contains("var _this = this;"), "missing hoisted _this:\n{}", out);
+    assert!(
+        out.contains("return _this;") || out.contains("return _this"),
+        "converted arrow doesn't return _this:\n{}",
+        out
+    );
+
+    assert_fold_round_trips(
+        arrow(),
+        r#"
+        function outer() {
+            console.log(this);
+            return () => this;
+        }
+        "#,
+    );
+}
+
+#[test]
+fn captures_arguments_and_new_target() {
+    let out = print_fold(
+        arrow(),
+        r#"
+        function outer() {
+            return () => [arguments.length, new.target];
+        }
+        "#,
+    );
+
+    assert!(
+        out.contains("var _arguments = arguments;"),
+        "missing hoisted _arguments:\n{}",
+        out
+    );
+    assert!(
+        out.contains("var _newtarget = new.target;"),
+        "missing hoisted _newtarget:\n{}",
+        out
+    );
+    assert!(
+        out.contains("_arguments.length"),
+        "converted body doesn't use _arguments:\n{}",
+        out
+    );
+    assert!(out.contains("_newtarget"), "converted body doesn't use _newtarget:\n{}", out);
+    // The bare, uncaptured forms must not remain inside the converted body.
+    assert!(
+        !out.contains("[arguments.length, new.target]"),
+        "bare arguments/new.target survived lowering:\n{}",
+        out
+    );
+
+    assert_fold_round_trips(
+        arrow(),
+        r#"
+        function outer() {
+            return () => [arguments.length, new.target];
+        }
+        "#,
+    );
+}
+
+#[test]
+fn captures_super_prop_access_and_calls() {
+    let out = print_fold(
+        arrow(),
+        r#"
+        var obj = {
+            method() {
+                return () => super.method() + super.value;
+            }
+        };
+        "#,
+    );
+
+    assert!(
+        out.contains("var _superprop_getMethod = super.method;"),
+        "missing hoisted _superprop_getMethod:\n{}",
+        out
+    );
+    assert!(
+        out.contains("var _superprop_getValue = super.value;"),
+        "missing hoisted _superprop_getValue:\n{}",
+        out
+    );
+    // The call must go through the original receiver, via the hoisted
+    // `_this`, not a bare `this` of the new unbound function.
+    assert!(
+        out.contains("var _this = this;"),
+        "super.method() call should have hoisted _this as its receiver:\n{}",
+        out
+    );
+    assert!(
+        out.contains("_superprop_getMethod.call(_this)"),
+        "converted call doesn't use _this as its receiver:\n{}",
+        out
+    );
+    assert!(
+        out.contains("_superprop_getValue"),
+        "converted body doesn't use the hoisted super.value capture:\n{}",
+        out
+    );
+    // No literal `super` should remain inside the converted (plain
+    // `function`) body - it would be a SyntaxError there. The hoisted
+    // captures above it legitimately read `super.`, so only the converted
+    // function onwards is checked.
+    let body_at = out.find("function").expect("converted function missing");
+    assert!(
+        !out[body_at..].contains("super."),
+        "literal `super` survived lowering into a plain function:\n{}",
+        out
+    );
+
+    assert_fold_round_trips(
+        arrow(),
+        r#"
+        var obj = {
+            method() {
+                return () => super.method() + super.value;
+            }
+        };
+        "#,
+    );
+}
+
+#[test]
+fn hoists_this_after_leading_super_call_in_constructor() {
+    let out = print_fold(
+        arrow(),
+        r#"
+        class Child extends Parent {
+            constructor(props) {
+                super(props);
+                this.onClick = () => this.handleClick();
+            }
+        }
+        "#,
+    );
+
+    // `super(...)` must still run first - `this` isn't available until it
+    // returns, so hoisting `var _this = this;` ahead of it would throw
+    // "must call super constructor before accessing 'this'" at runtime.
+    let super_at = out.find("super(props)").expect("super call missing");
+    let hoisted_at = out.find("var _this = this;").expect("missing hoisted _this");
+    assert!(
+        super_at < hoisted_at,
+        "hoisted _this must come after the super() call:\n{}",
+        out
+    );
+
+    assert_fold_round_trips(
+        arrow(),
+        r#"
+        class Child extends Parent {
+            constructor(props) {
+                super(props);
+                this.onClick = () => this.handleClick();
+            }
+        }
+        "#,
+    );
+}
+
+#[test]
+fn avoids_colliding_with_an_existing_this_binding() {
+    let out = print_fold(
+        arrow(),
+        r#"
+        function outer() {
+            var _this = computeSomething();
+            return () => this.x;
+        }
+        "#,
+    );
+
+    // The user's own `_this` must survive untouched, and the hoisted
+    // capture must pick a name that doesn't merge with it (`var` bindings
+    // of the same name in the same scope would otherwise collide).
+    assert!(
+        out.contains("var _this = computeSomething();"),
+        "existing _this binding was clobbered:\n{}",
+        out
+    );
+    assert!(
+        out.contains("var _this2 = this;"),
+        "hoisted capture should have been renamed to avoid collision:\n{}",
+        out
+    );
+    assert!(out.contains("_this2.x"), "converted body doesn't use the renamed capture:\n{}", out);
+
+    assert_fold_round_trips(
+        arrow(),
+        r#"
+        function outer() {
+            var _this = computeSomething();
+            return () => this.x;
+        }
+        "#,
+    );
+}
+
+#[test]
+fn rejects_computed_super_access_in_arrows() {
+    // A computed `super[prop]` can't be captured, but it must be reported as
+    // a normal compile error rather than panicking the whole process - one
+    // file using it shouldn't be able to take down an otherwise-unrelated
+    // build.
+    let messages = assert_fold_reports_errors(
+        arrow(),
+        r#"
+        var obj = {
+            method() {
+                return () => super[computedKey];
+            }
+        };
+        "#,
+    );
+
+    assert!(
+        messages.iter().any(|m| m.contains("computed") && m.contains("super")),
+        "unexpected diagnostics: {:?}",
+        messages
+    );
+}
+
+#[test]
+fn preserves_precedence_of_converted_arrow_bodies() {
+    assert_fold_preserves_precedence(arrow(), "var f = () => 1 + 2 * 3;");
+}
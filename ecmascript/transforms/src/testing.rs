@@ -0,0 +1,403 @@
+//! Test utilities shared by this crate's transform test suites.
+//!
+//! Comparing transform output as source strings is brittle against harmless
+//! formatting differences and proves nothing about whether the emitted AST
+//! is actually re-parseable. `SpanlessEq`, together with
+//! `assert_fold_round_trips` and `assert_fold_preserves_precedence`, borrow
+//! the differential-testing technique used by syn's precedence tests: run
+//! the fold, print it, re-parse the printed source, and compare trees
+//! structurally instead of textually.
+//!
+//! This checkout doesn't carry a workspace manifest, so these helpers and
+//! the suites built on them have only been typechecked by eye against the
+//! `ast`/`swc_ecma_parser`/`swc_ecma_codegen`/`swc_common` APIs they call,
+//! not actually compiled or run. Confirm `cargo test` is green against the
+//! real crates before merging anything that touches this file.
+
+use ast::*;
+use std::sync::{Arc, Mutex};
+use swc_common::errors::{Emitter as DiagnosticEmitter, Handler, HANDLER};
+use swc_common::{Fold, FoldWith, SourceMap, FileName, DUMMY_SP};
+use swc_ecma_codegen::{text_writer::JsWriter, Emitter};
+use swc_ecma_parser::{lexer::Lexer, Parser, StringInput, Syntax};
+
+/// Structural equality between two AST nodes that ignores every `Span`.
+///
+/// This covers identifiers, `this`, `super`, `new.target`, member, call,
+/// arrow and function expressions, blocks, `var` declarations, `return`
+/// and expression statements, and identifier patterns. Object literals,
+/// binary expressions, literals, arrays, assignments, and function or class
+/// declarations are not covered: two nodes of a variant this trait doesn't
+/// know how to compare are conservatively treated as unequal (failing loud)
+/// rather than silently treated as equal. Extend a `match`
+/// arm as a transform's tests need it. Once `ast` grows a derive for this
+/// (the same way it already derives `Fold`/`FoldWith`), these hand-written
+/// impls should be replaced by `#[derive(SpanlessEq)]`.
+pub trait SpanlessEq {
+    fn eq_ignore_span(&self, other: &Self) -> bool;
+}
+
+impl SpanlessEq for swc_common::Span {
+    fn eq_ignore_span(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl<T: SpanlessEq> SpanlessEq for Box<T> {
+    fn eq_ignore_span(&self, other: &Self) -> bool {
+        (**self).eq_ignore_span(other)
+    }
+}
+
+impl<T: SpanlessEq> SpanlessEq for Option<T> {
+    fn eq_ignore_span(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Some(a), Some(b)) => a.eq_ignore_span(b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl<T: SpanlessEq> SpanlessEq for Vec<T> {
+    fn eq_ignore_span(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().zip(other).all(|(a, b)| a.eq_ignore_span(b))
+    }
+}
+
+impl SpanlessEq for Ident {
+    fn eq_ignore_span(&self, other: &Self) -> bool {
+        self.sym == other.sym
+    }
+}
+
+impl SpanlessEq for ThisExpr {
+    fn eq_ignore_span(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl SpanlessEq for Super {
+    fn eq_ignore_span(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl SpanlessEq for MetaPropExpr {
+    fn eq_ignore_span(&self, other: &Self) -> bool {
+        self.meta.eq_ignore_span(&other.meta) && self.prop.eq_ignore_span(&other.prop)
+    }
+}
+
+impl SpanlessEq for ExprOrSuper {
+    fn eq_ignore_span(&self, other: &Self) -> bool {
+        match (self, other) {
+            (ExprOrSuper::Expr(a), ExprOrSuper::Expr(b)) => a.eq_ignore_span(b),
+            (ExprOrSuper::Super(a), ExprOrSuper::Super(b)) => a.eq_ignore_span(b),
+            _ => false,
+        }
+    }
+}
+
+impl SpanlessEq for MemberExpr {
+    fn eq_ignore_span(&self, other: &Self) -> bool {
+        self.computed == other.computed
+            && self.obj.eq_ignore_span(&other.obj)
+            && self.prop.eq_ignore_span(&other.prop)
+    }
+}
+
+impl SpanlessEq for ExprOrSpread {
+    fn eq_ignore_span(&self, other: &Self) -> bool {
+        self.spread.is_some() == other.spread.is_some() && self.expr.eq_ignore_span(&other.expr)
+    }
+}
+
+impl SpanlessEq for CallExpr {
+    fn eq_ignore_span(&self, other: &Self) -> bool {
+        self.callee.eq_ignore_span(&other.callee) && self.args.eq_ignore_span(&other.args)
+    }
+}
+
+impl SpanlessEq for ArrowExpr {
+    fn eq_ignore_span(&self, other: &Self) -> bool {
+        self.is_async == other.is_async
+            && self.is_generator == other.is_generator
+            && self.params.eq_ignore_span(&other.params)
+            && self.body.eq_ignore_span(&other.body)
+    }
+}
+
+impl SpanlessEq for FnExpr {
+    fn eq_ignore_span(&self, other: &Self) -> bool {
+        self.ident.eq_ignore_span(&other.ident) && self.function.eq_ignore_span(&other.function)
+    }
+}
+
+impl SpanlessEq for Function {
+    fn eq_ignore_span(&self, other: &Self) -> bool {
+        self.is_async == other.is_async
+            && self.is_generator == other.is_generator
+            && self.params.eq_ignore_span(&other.params)
+            && self.body.eq_ignore_span(&other.body)
+    }
+}
+
+impl SpanlessEq for BlockStmtOrExpr {
+    fn eq_ignore_span(&self, other: &Self) -> bool {
+        match (self, other) {
+            (BlockStmtOrExpr::BlockStmt(a), BlockStmtOrExpr::BlockStmt(b)) => a.eq_ignore_span(b),
+            (BlockStmtOrExpr::Expr(a), BlockStmtOrExpr::Expr(b)) => a.eq_ignore_span(b),
+            _ => false,
+        }
+    }
+}
+
+impl SpanlessEq for BlockStmt {
+    fn eq_ignore_span(&self, other: &Self) -> bool {
+        self.stmts.eq_ignore_span(&other.stmts)
+    }
+}
+
+impl SpanlessEq for Pat {
+    fn eq_ignore_span(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Pat::Ident(a), Pat::Ident(b)) => a.eq_ignore_span(b),
+            _ => false,
+        }
+    }
+}
+
+impl SpanlessEq for VarDeclKind {
+    /// `VarDeclKind` carries no data, so matching variants by discriminant
+    /// is exact - no need to know its variants' names here.
+    fn eq_ignore_span(&self, other: &Self) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+}
+
+impl SpanlessEq for VarDeclarator {
+    fn eq_ignore_span(&self, other: &Self) -> bool {
+        self.name.eq_ignore_span(&other.name) && self.init.eq_ignore_span(&other.init)
+    }
+}
+
+impl SpanlessEq for VarDecl {
+    fn eq_ignore_span(&self, other: &Self) -> bool {
+        self.kind.eq_ignore_span(&other.kind) && self.decls.eq_ignore_span(&other.decls)
+    }
+}
+
+impl SpanlessEq for Decl {
+    fn eq_ignore_span(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Decl::Var(a), Decl::Var(b)) => a.eq_ignore_span(b),
+            _ => false,
+        }
+    }
+}
+
+impl SpanlessEq for ReturnStmt {
+    fn eq_ignore_span(&self, other: &Self) -> bool {
+        self.arg.eq_ignore_span(&other.arg)
+    }
+}
+
+impl SpanlessEq for ExprStmt {
+    fn eq_ignore_span(&self, other: &Self) -> bool {
+        self.expr.eq_ignore_span(&other.expr)
+    }
+}
+
+impl SpanlessEq for Stmt {
+    fn eq_ignore_span(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Stmt::Return(a), Stmt::Return(b)) => a.eq_ignore_span(b),
+            (Stmt::Decl(a), Stmt::Decl(b)) => a.eq_ignore_span(b),
+            (Stmt::Expr(a), Stmt::Expr(b)) => a.eq_ignore_span(b),
+            _ => false,
+        }
+    }
+}
+
+impl SpanlessEq for Expr {
+    fn eq_ignore_span(&self, other: &Self) -> bool {
+        // Parens never change AST shape, only how it prints, so they're
+        // transparent to structural equality - this is what lets
+        // `assert_fold_preserves_precedence` compare a fold's output
+        // against the same fold run on an input with every subexpression
+        // wrapped in synthetic parens.
+        fn unwrap_parens(mut e: &Expr) -> &Expr {
+            while let Expr::Paren(ParenExpr { expr, .. }) = e {
+                e = expr;
+            }
+            e
+        }
+
+        match (unwrap_parens(self), unwrap_parens(other)) {
+            (Expr::This(a), Expr::This(b)) => a.eq_ignore_span(b),
+            (Expr::Ident(a), Expr::Ident(b)) => a.eq_ignore_span(b),
+            (Expr::MetaProp(a), Expr::MetaProp(b)) => a.eq_ignore_span(b),
+            (Expr::Member(a), Expr::Member(b)) => a.eq_ignore_span(b),
+            (Expr::Call(a), Expr::Call(b)) => a.eq_ignore_span(b),
+            (Expr::Arrow(a), Expr::Arrow(b)) => a.eq_ignore_span(b),
+            (Expr::Fn(a), Expr::Fn(b)) => a.eq_ignore_span(b),
+            _ => false,
+        }
+    }
+}
+
+impl SpanlessEq for ModuleItem {
+    fn eq_ignore_span(&self, other: &Self) -> bool {
+        match (self, other) {
+            (ModuleItem::Stmt(a), ModuleItem::Stmt(b)) => a.eq_ignore_span(b),
+            _ => false,
+        }
+    }
+}
+
+impl SpanlessEq for Module {
+    fn eq_ignore_span(&self, other: &Self) -> bool {
+        self.body.eq_ignore_span(&other.body)
+    }
+}
+
+fn parse(src: &str) -> Module {
+    let cm: SourceMap = Default::default();
+    let fm = cm.new_source_file(FileName::Anon, src.into());
+    let lexer = Lexer::new(Syntax::default(), Default::default(), StringInput::from(&*fm), None);
+    let mut parser = Parser::new_from(lexer);
+
+    parser
+        .parse_module()
+        .expect("failed to parse transform test input")
+}
+
+fn print(module: &Module) -> String {
+    let cm: SourceMap = Default::default();
+    let mut buf = vec![];
+
+    {
+        let mut emitter = Emitter {
+            cfg: Default::default(),
+            cm: &cm,
+            comments: None,
+            wr: Box::new(JsWriter::new(&cm, "\n", &mut buf, None)),
+        };
+        emitter
+            .emit_module(module)
+            .expect("failed to print transform test output");
+    }
+
+    String::from_utf8(buf).expect("codegen produced invalid utf8")
+}
+
+/// Runs `fold` over `src` and returns the printed source, for tests that
+/// need to inspect what the transform actually produced (hoisted
+/// declarations, identifiers that should or shouldn't appear anymore, ...)
+/// rather than only checking that it round-trips or preserves precedence.
+pub fn print_fold<F>(mut fold: F, src: &str) -> String
+where
+    F: Fold<Module>,
+{
+    print(&fold.fold(parse(src)))
+}
+
+/// Runs `fold` over `src`, then asserts that printing its output and
+/// re-parsing that printed source round-trips back to a spanless-equal
+/// tree. Catches a transform emitting an AST the printer/parser pair can't
+/// faithfully reproduce - something a fixture string diff can't see, since
+/// it only ever looks at the printed text, never at what re-parsing it
+/// actually yields.
+pub fn assert_fold_round_trips<F>(mut fold: F, src: &str)
+where
+    F: Fold<Module>,
+{
+    let folded = fold.fold(parse(src));
+    let printed = print(&folded);
+    let reparsed = parse(&printed);
+
+    assert!(
+        reparsed.eq_ignore_span(&folded),
+        "`{}` produced output that doesn't round-trip through the printer/parser:\n{}",
+        src,
+        printed
+    );
+}
+
+/// A `swc_common::errors::Emitter` that stashes every diagnostic's rendered
+/// message instead of printing it, so a test can assert on what a fold
+/// reported through `HANDLER` without that noise going to stderr.
+#[derive(Clone, Default)]
+struct CapturingEmitter(Arc<Mutex<Vec<String>>>);
+
+impl DiagnosticEmitter for CapturingEmitter {
+    fn emit(&mut self, db: &swc_common::errors::DiagnosticBuilder) {
+        self.0.lock().unwrap().push(db.message());
+    }
+}
+
+/// Runs `fold` over `src` under a `HANDLER` backed by a `CapturingEmitter`,
+/// and returns the messages of every diagnostic it reported. For transforms
+/// that recover from unsupported input by emitting an error and passing the
+/// node through unchanged, rather than panicking.
+pub fn assert_fold_reports_errors<F>(mut fold: F, src: &str) -> Vec<String>
+where
+    F: Fold<Module>,
+{
+    let cm: SourceMap = Default::default();
+    let messages: Arc<Mutex<Vec<String>>> = Default::default();
+    let handler = Handler::with_emitter(
+        true,
+        false,
+        Box::new(CapturingEmitter(messages.clone())),
+    );
+
+    HANDLER.set(&handler, || {
+        fold.fold(parse(src));
+    });
+
+    let messages = messages.lock().unwrap().clone();
+    assert!(
+        !messages.is_empty(),
+        "`{}` was expected to report a diagnostic, but none were emitted",
+        src
+    );
+    messages
+}
+
+/// Wraps every subexpression it visits in a synthetic `( ... )`.
+#[derive(Debug, Clone, Copy)]
+struct Parenthesize;
+
+impl Fold<Expr> for Parenthesize {
+    fn fold(&mut self, e: Expr) -> Expr {
+        let e = e.fold_children(self);
+
+        Expr::Paren(ParenExpr {
+            span: DUMMY_SP,
+            expr: box e,
+        })
+    }
+}
+
+/// A cheap fuzzer for precedence/associativity bugs: folds `src` as-is, then
+/// folds it again with every subexpression first wrapped in a synthetic
+/// paren, and asserts the two results have the same structure. A fold that
+/// only worked by accident of the input's original grouping - e.g. one that
+/// assumes an operand can't itself be a lower-precedence expression - tends
+/// to fall over once that assumption is broken by the added parens.
+pub fn assert_fold_preserves_precedence<F>(mut fold: F, src: &str)
+where
+    F: Fold<Module>,
+{
+    let bare = fold.fold(parse(src));
+    let parenthesized = fold.fold(parse(src).fold_with(&mut Parenthesize));
+
+    assert!(
+        bare.eq_ignore_span(&parenthesized),
+        "parenthesizing every subexpression of `{}` changed the folded structure - \
+         possible precedence/associativity bug",
+        src
+    );
+}